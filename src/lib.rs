@@ -3,51 +3,206 @@
 //! for Rust.
 
 extern crate time;
+#[cfg(feature = "serde")]
+extern crate serde;
 
+use std::fmt;
 use std::fmt::{Formatter, Display, Error};
+use std::error;
+use std::str::FromStr;
 use std::sync::Mutex;
 
+/// The length, in bytes, of `HLTimespec`'s fixed-size binary encoding.
+pub const ENCODED_LEN: usize = 26;
+
 /// The `HLTimespec` type stores a hybrid logical timestamp (also called
 /// timespec for symmetry with time::Timespec).
 ///
-/// Such a timestamp is comprised of an "ordinary" wall time and
-/// a logical component. Timestamps are compared by wall time first,
-/// logical second.
+/// Such a timestamp is comprised of an epoch, an "ordinary" wall time, a
+/// logical component, and a node identifier. Timestamps are compared by
+/// epoch first, wall time second, logical third, and node identifier
+/// last, so that two timestamps generated on different nodes at the same
+/// wall/logical instant are never equal, making them usable directly as
+/// globally unique event keys. The epoch exists to let an operator recover
+/// from a clock that has been pushed arbitrarily far into the future: see
+/// `State::set_epoch`.
 ///
 /// # Examples
 ///
 /// ```
 /// use hlc::HLTimespec;
-/// let early = HLTimespec::new(1, 0, 0);
-/// let middle = HLTimespec::new(1, 1, 0);
-/// let late = HLTimespec::new(1, 1, 1);
+/// let early = HLTimespec::new(0, 1, 0, 0, 0);
+/// let middle = HLTimespec::new(0, 1, 1, 0, 0);
+/// let late = HLTimespec::new(0, 1, 1, 1, 0);
 /// assert!(early < middle && middle < late);
+/// assert!(late < HLTimespec::new(1, 0, 0, 0, 0)); // a later epoch always sorts last
 /// ```
 #[derive(Debug,Clone,Copy,Eq,PartialEq,PartialOrd,Ord)]
 pub struct HLTimespec {
+    epoch: u32,
     wall: time::Timespec,
     logical: u16,
+    id: u64,
 }
 
 impl HLTimespec {
-    /// Creates a new hybrid logical timestamp with the given seconds,
-    /// nanoseconds, and logical ticks.
+    /// Creates a new hybrid logical timestamp with the given epoch,
+    /// seconds, nanoseconds, logical ticks, and node identifier.
     ///
     /// # Examples
     ///
     /// ```
     /// use hlc::HLTimespec;
-    /// let ts = HLTimespec::new(1, 2, 3);
-    /// assert_eq!(format!("{}", ts), "1.2+3");
+    /// let ts = HLTimespec::new(0, 1, 2, 3, 4);
+    /// assert_eq!(format!("{}", ts), "0:1.2+3@4");
     /// ```
-    pub fn new(s: i64, ns: i32, l: u16) -> HLTimespec {
-        HLTimespec { wall: time::Timespec { sec: s, nsec: ns }, logical: l }
+    pub fn new(epoch: u32, s: i64, ns: i32, l: u16, id: u64) -> HLTimespec {
+        HLTimespec { epoch: epoch, wall: time::Timespec { sec: s, nsec: ns }, logical: l, id: id }
+    }
+
+    /// Encodes this timestamp into a fixed-size, big-endian binary layout
+    /// (4 bytes epoch, 8 bytes seconds, 4 bytes nanoseconds, 2 bytes
+    /// logical, 8 bytes node identifier) chosen so that the lexicographic
+    /// ordering of the raw bytes matches this type's `Ord` impl. This makes
+    /// the encoding usable directly as a sort key in byte-ordered
+    /// key-value stores, as long as `nsec` is kept in its normal
+    /// `0..1_000_000_000` range, as `time::get_time` and this crate's own
+    /// constructors always produce.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hlc::HLTimespec;
+    /// let a = HLTimespec::new(0, 1, 0, 0, 0);
+    /// let b = HLTimespec::new(0, 1, 0, 0, 1);
+    /// assert!(a.encode() < b.encode());
+    /// ```
+    pub fn encode(&self) -> [u8; ENCODED_LEN] {
+        let mut buf = [0u8; ENCODED_LEN];
+        buf[0..4].copy_from_slice(&self.epoch.to_be_bytes());
+        let sec_biased = (self.wall.sec as u64) ^ 0x8000_0000_0000_0000;
+        buf[4..12].copy_from_slice(&sec_biased.to_be_bytes());
+        buf[12..16].copy_from_slice(&(self.wall.nsec as u32).to_be_bytes());
+        buf[16..18].copy_from_slice(&self.logical.to_be_bytes());
+        buf[18..26].copy_from_slice(&self.id.to_be_bytes());
+        buf
+    }
+
+    /// Decodes a timestamp previously produced by `encode`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hlc::HLTimespec;
+    /// let ts = HLTimespec::new(0, 1, 2, 3, 4);
+    /// assert_eq!(HLTimespec::decode(&ts.encode()).unwrap(), ts);
+    /// ```
+    pub fn decode(bytes: &[u8]) -> Result<HLTimespec, ParseError> {
+        if bytes.len() != ENCODED_LEN {
+            return Err(ParseError::InvalidLength { expected: ENCODED_LEN, found: bytes.len() });
+        }
+
+        let mut epoch_bytes = [0u8; 4];
+        epoch_bytes.copy_from_slice(&bytes[0..4]);
+        let epoch = u32::from_be_bytes(epoch_bytes);
+
+        let mut sec_bytes = [0u8; 8];
+        sec_bytes.copy_from_slice(&bytes[4..12]);
+        let sec = (u64::from_be_bytes(sec_bytes) ^ 0x8000_0000_0000_0000) as i64;
+
+        let mut nsec_bytes = [0u8; 4];
+        nsec_bytes.copy_from_slice(&bytes[12..16]);
+        let nsec = u32::from_be_bytes(nsec_bytes) as i32;
+
+        let mut logical_bytes = [0u8; 2];
+        logical_bytes.copy_from_slice(&bytes[16..18]);
+        let logical = u16::from_be_bytes(logical_bytes);
+
+        let mut id_bytes = [0u8; 8];
+        id_bytes.copy_from_slice(&bytes[18..26]);
+        let id = u64::from_be_bytes(id_bytes);
+
+        Ok(HLTimespec { epoch: epoch, wall: time::Timespec { sec: sec, nsec: nsec }, logical: logical, id: id })
     }
 }
 
 impl Display for HLTimespec {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        f.write_str(&format!("{}.{}+{}", self.wall.sec, self.wall.nsec, self.logical))
+        f.write_str(&format!("{}:{}.{}+{}@{}", self.epoch, self.wall.sec, self.wall.nsec, self.logical, self.id))
+    }
+}
+
+impl FromStr for HLTimespec {
+    type Err = ParseError;
+
+    /// Parses the `Display` format (`"epoch:sec.nsec+logical@id"`) back
+    /// into an `HLTimespec`, making the round trip through `to_string`
+    /// lossless.
+    fn from_str(s: &str) -> Result<HLTimespec, ParseError> {
+        let colon = s.find(':').ok_or(ParseError::InvalidFormat)?;
+        let (epoch_part, s) = (&s[..colon], &s[colon+1..]);
+
+        let at = s.rfind('@').ok_or(ParseError::InvalidFormat)?;
+        let (ts_part, id_part) = (&s[..at], &s[at+1..]);
+
+        let dot = ts_part.find('.').ok_or(ParseError::InvalidFormat)?;
+        let (sec_part, rest) = (&ts_part[..dot], &ts_part[dot+1..]);
+
+        let plus = rest.find('+').ok_or(ParseError::InvalidFormat)?;
+        let (nsec_part, logical_part) = (&rest[..plus], &rest[plus+1..]);
+
+        let epoch = epoch_part.parse::<u32>().map_err(|_| ParseError::InvalidFormat)?;
+        let sec = sec_part.parse::<i64>().map_err(|_| ParseError::InvalidFormat)?;
+        let nsec = nsec_part.parse::<i32>().map_err(|_| ParseError::InvalidFormat)?;
+        let logical = logical_part.parse::<u16>().map_err(|_| ParseError::InvalidFormat)?;
+        let id = id_part.parse::<u64>().map_err(|_| ParseError::InvalidFormat)?;
+
+        Ok(HLTimespec { epoch: epoch, wall: time::Timespec { sec: sec, nsec: nsec }, logical: logical, id: id })
+    }
+}
+
+/// The error returned when parsing or decoding an `HLTimespec` fails.
+#[derive(Debug,Clone,Eq,PartialEq)]
+pub enum ParseError {
+    /// `decode` was given a byte slice of the wrong length.
+    InvalidLength { expected: usize, found: usize },
+    /// The input did not match the `"epoch:sec.nsec+logical@id"` format.
+    InvalidFormat,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            ParseError::InvalidLength { expected, found } =>
+                write!(f, "expected {} bytes, found {}", expected, found),
+            ParseError::InvalidFormat =>
+                write!(f, "input did not match the \"epoch:sec.nsec+logical@id\" format"),
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        "failed to parse an HLTimespec"
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HLTimespec {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.encode())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for HLTimespec {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<HLTimespec, D::Error> {
+        // Deserialize into an owned buffer rather than `&[u8]`: borrowing
+        // only round-trips through zero-copy binary formats, while an owned
+        // `Vec<u8>` also works with formats that can't hand back a borrow,
+        // such as self-describing or human-readable ones.
+        let bytes = <Vec<u8>>::deserialize(deserializer)?;
+        HLTimespec::decode(&bytes).map_err(serde::de::Error::custom)
     }
 }
 
@@ -59,8 +214,8 @@ impl Display for HLTimespec {
 /// use hlc::{HLTimespec, State};
 /// let mut s = State::new();
 /// println!("{}", s.get_time()); // attach to outgoing event
-/// let ext_event_ts = HLTimespec::new(12345, 67, 89); // external event's timestamp
-/// let ext_event_recv_ts = s.update(ext_event_ts);
+/// let ext_event_ts = HLTimespec::new(0, 12345, 67, 89, 1); // external event's timestamp
+/// let ext_event_recv_ts = s.update(ext_event_ts).unwrap();
 /// ```
 ///
 /// If access to the clock isn't serializable, a convenience method returns
@@ -77,6 +232,62 @@ impl Display for HLTimespec {
 pub struct State<F> {
     s: HLTimespec,
     now: F,
+    max_diff: time::Duration,
+}
+
+/// The error returned by `State::update` when a remote timestamp is
+/// rejected.
+#[derive(Debug,Clone,Copy,Eq,PartialEq)]
+pub enum UpdateError {
+    /// The event's wall time is ahead of the local wall clock by more than
+    /// the clock's configured `max_diff`, by `diff`.
+    TooFarAhead { diff: time::Duration },
+}
+
+impl Display for UpdateError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            UpdateError::TooFarAhead { diff } =>
+                write!(f, "event is {:?} ahead of the local clock, which exceeds the allowed maximum offset", diff),
+        }
+    }
+}
+
+impl error::Error for UpdateError {
+    fn description(&self) -> &str {
+        "event's wall time exceeds the allowed maximum offset from the local clock"
+    }
+}
+
+/// Advances the logical counter by one tick, carrying into `wall` by a
+/// single nanosecond (and from there into `sec` as needed) instead of
+/// overflowing when `logical` is already at `u16::MAX`. This preserves the
+/// clock's strict monotonicity guarantee across more than 65535 events
+/// within the same wall-clock nanosecond.
+fn tick(wall: &mut time::Timespec, logical: u16) -> u16 {
+    if logical == u16::MAX {
+        *wall = if wall.nsec == 999_999_999 {
+            time::Timespec { sec: wall.sec + 1, nsec: 0 }
+        } else {
+            time::Timespec { sec: wall.sec, nsec: wall.nsec + 1 }
+        };
+        0
+    } else {
+        logical + 1
+    }
+}
+
+/// Computes `later - earlier` as a `Duration`, given that `later > earlier`,
+/// without panicking when the two are so far apart that the difference
+/// doesn't fit in a `Duration` (e.g. a bogus remote timestamp near
+/// `i64::MAX`). Such differences saturate to `Duration::max_value()`, which
+/// is always further away than any finite `max_diff`, so callers only use
+/// this to test against a bound rather than relying on its exact value.
+fn saturating_wall_diff(later: time::Timespec, earlier: time::Timespec) -> time::Duration {
+    match later.sec.checked_sub(earlier.sec) {
+        Some(secs) if secs < time::Duration::max_value().num_seconds() => later - earlier,
+        _ => time::Duration::max_value(),
+    }
 }
 
 impl State<()> {
@@ -96,6 +307,9 @@ impl<F: FnMut() -> time::Timespec> State<F> {
     /// Creates a hybrid logical clock with the supplied wall time. This is
     /// useful for tests or settings in which an alternative clock is used.
     ///
+    /// The clock's node identifier defaults to `0`; use `new_with_id` to
+    /// give it a unique identifier instead.
+    ///
     /// # Examples
     ///
     /// ```
@@ -106,16 +320,87 @@ impl<F: FnMut() -> time::Timespec> State<F> {
     /// let mut times = vec![time::Timespec { sec: 42, nsec: 9919 }];
     /// let mut s = State::new_with(move || times.pop().unwrap());
     /// let mut ts = s.get_time();
-    /// assert_eq!(format!("{}", ts), "42.9919+0");
+    /// assert_eq!(format!("{}", ts), "0:42.9919+0@0");
     /// # }
     /// ```
     pub fn new_with(now: F) -> State<F> {
+        State::new_with_id(now, 0)
+    }
+
+    /// Creates a hybrid logical clock with the supplied wall time and node
+    /// identifier. The identifier is stamped into every timestamp this
+    /// clock produces and acts as the final tie-breaker in comparisons,
+    /// guaranteeing that timestamps generated on different nodes are never
+    /// equal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate hlc;
+    /// # extern crate time;
+    /// # fn main() {
+    /// use hlc::{HLTimespec, State};
+    /// let mut times = vec![time::Timespec { sec: 42, nsec: 9919 }];
+    /// let mut s = State::new_with_id(move || times.pop().unwrap(), 7);
+    /// let mut ts = s.get_time();
+    /// assert_eq!(format!("{}", ts), "0:42.9919+0@7");
+    /// # }
+    /// ```
+    pub fn new_with_id(now: F, id: u64) -> State<F> {
+        State::new_with_id_and_max_diff(now, id, time::Duration::max_value())
+    }
+
+    /// Creates a hybrid logical clock with the supplied wall time and a
+    /// maximum allowed offset between a remote event's wall time and the
+    /// local wall clock. `update` rejects any event whose wall time is
+    /// ahead of the local clock by more than `max_diff`, returning
+    /// `UpdateError::TooFarAhead` instead of adopting it. This guards
+    /// against a single misbehaving or malicious peer dragging the local
+    /// clock arbitrarily far into the future.
+    ///
+    /// The clock's node identifier defaults to `0`; use
+    /// `new_with_id_and_max_diff` to set both.
+    pub fn new_with_max_diff(now: F, max_diff: time::Duration) -> State<F> {
+        State::new_with_id_and_max_diff(now, 0, max_diff)
+    }
+
+    /// Creates a hybrid logical clock with the supplied wall time, node
+    /// identifier, and maximum allowed offset. See `new_with_id` and
+    /// `new_with_max_diff` for what each parameter controls.
+    pub fn new_with_id_and_max_diff(now: F, id: u64, max_diff: time::Duration) -> State<F> {
         State {
-            s: HLTimespec { wall: time::Timespec { sec: 0, nsec: 0 }, logical: 0 },
+            s: HLTimespec { epoch: 0, wall: time::Timespec { sec: 0, nsec: 0 }, logical: 0, id: id },
             now: now,
+            max_diff: max_diff,
         }
     }
 
+    /// Overrides the clock's epoch, the most-significant component of
+    /// every timestamp it produces from this point on. Timestamps at a
+    /// higher epoch always sort after timestamps at a lower one,
+    /// regardless of wall time, so bumping the epoch lets a node recover
+    /// from a peer that has dragged the cluster's clock arbitrarily far
+    /// into the future: it can resume issuing timestamps anchored to the
+    /// real current wall time while still guaranteeing they sort after
+    /// everything previously seen.
+    ///
+    /// The wall and logical components are reset so that the next
+    /// `get_time`/`update` call picks up the real current wall time rather
+    /// than continuing from the (possibly skewed) previous state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hlc::State;
+    /// let mut s = State::new();
+    /// s.set_epoch(1);
+    /// ```
+    pub fn set_epoch(&mut self, epoch: u32) {
+        self.s.epoch = epoch;
+        self.s.wall = time::Timespec { sec: 0, nsec: 0 };
+        self.s.logical = 0;
+    }
+
     /// Generates a timestamp from the clock.
     pub fn get_time(&mut self) -> HLTimespec {
         let s = &mut self.s;
@@ -124,45 +409,69 @@ impl<F: FnMut() -> time::Timespec> State<F> {
             s.wall = wall;
             s.logical = 0;
         } else {
-            s.logical += 1;
+            s.logical = tick(&mut s.wall, s.logical);
         }
         s.clone()
     }
 
     /// Assigns a timestamp to an event which happened at the given timestamp
     /// on a remote system.
-    pub fn update(&mut self, event: HLTimespec) -> HLTimespec {
-        let (wall, s) = ((self.now)(), &mut self.s);
+    ///
+    /// If `event`'s epoch is greater than this clock's, it is adopted,
+    /// just as a greater wall time would be. If `event`'s epoch is lower,
+    /// it is stale and only advances the local logical counter, ignoring
+    /// its wall time and logical value entirely.
+    ///
+    /// Returns `Err(UpdateError::TooFarAhead)`, leaving the clock's state
+    /// unmodified, if `event` is at the same epoch as this clock and its
+    /// wall time is ahead of the local wall clock by more than this
+    /// clock's `max_diff`. A jump introduced by adopting a greater epoch
+    /// is never subject to this check.
+    pub fn update(&mut self, event: HLTimespec) -> Result<HLTimespec, UpdateError> {
+        let wall = (self.now)();
 
-        if wall > event.wall && wall > s.wall {
+        if event.epoch == self.s.epoch && event.wall > wall {
+            let diff = saturating_wall_diff(event.wall, wall);
+            if diff > self.max_diff {
+                return Err(UpdateError::TooFarAhead { diff: diff });
+            }
+        }
+
+        let s = &mut self.s;
+        if event.epoch > s.epoch {
+            s.epoch = event.epoch;
+            s.wall = event.wall;
+            s.logical = tick(&mut s.wall, event.logical);
+        } else if event.epoch < s.epoch {
+            s.logical = tick(&mut s.wall, s.logical);
+        } else if wall > event.wall && wall > s.wall {
             s.wall = wall;
             s.logical = 0
         } else if event.wall > s.wall {
             s.wall = event.wall;
-            s.logical = event.logical+1;
+            s.logical = tick(&mut s.wall, event.logical);
         } else if s.wall > event.wall {
-            s.logical += 1;
+            s.logical = tick(&mut s.wall, s.logical);
         } else {
-            if event.logical > s.logical {
-                s.logical = event.logical;
-            }
-            s.logical += 1;
+            let logical = if event.logical > s.logical { event.logical } else { s.logical };
+            s.logical = tick(&mut s.wall, logical);
         }
-        s.clone()
+        Ok(s.clone())
     }
 }
 
 #[cfg(test)]
 mod tests {
     extern crate time;
-    use {HLTimespec, State};
+    use {HLTimespec, State, UpdateError, ParseError};
+    use std::str::FromStr;
 
     fn ts(s: i64, ns: i32) -> time::Timespec {
         time::Timespec { sec: s, nsec: ns }
     }
 
     fn hlts(s: i64, ns: i32, l: u16) -> HLTimespec {
-        HLTimespec::new(s, ns, l)
+        HLTimespec::new(0, s, ns, l, 0)
     }
 
     #[test]
@@ -194,9 +503,166 @@ mod tests {
             let t = if op.1 == zero {
                 s.get_time()
             } else {
-                s.update(op.1.clone())
+                s.update(op.1.clone()).unwrap()
             };
             assert_eq!(t, op.2);
         }
     }
+
+    #[test]
+    fn update_rejects_events_too_far_ahead() {
+        let mut times = vec![ts(10, 0), ts(10, 0)];
+        let mut s = State::new_with_max_diff(move || times.pop().unwrap(), time::Duration::seconds(5));
+
+        let far_future = hlts(100, 0, 0);
+        match s.update(far_future) {
+            Err(UpdateError::TooFarAhead { diff }) => assert_eq!(diff, time::Duration::seconds(90)),
+            other => panic!("expected TooFarAhead, got {:?}", other),
+        }
+
+        // Clock state must be left untouched by the rejected update.
+        let t = s.get_time();
+        assert_eq!(t, hlts(10, 0, 0));
+    }
+
+    #[test]
+    fn update_rejects_events_too_far_ahead_without_overflowing() {
+        let mut times = vec![ts(10, 0), ts(10, 0)];
+        let mut s = State::new_with_max_diff(move || times.pop().unwrap(), time::Duration::seconds(5));
+
+        // A wall time this far in the future would overflow `Duration` if
+        // subtracted directly; the rejection must still happen, not a panic.
+        let far_future = hlts(i64::max_value(), 0, 0);
+        match s.update(far_future) {
+            Err(UpdateError::TooFarAhead { diff }) => assert_eq!(diff, time::Duration::max_value()),
+            other => panic!("expected TooFarAhead, got {:?}", other),
+        }
+
+        // Clock state must be left untouched by the rejected update.
+        let t = s.get_time();
+        assert_eq!(t, hlts(10, 0, 0));
+    }
+
+    #[test]
+    fn get_time_carries_logical_overflow_into_wall() {
+        let mut times = vec![ts(1, 0)];
+        let mut s = State::new_with(move || times.pop().unwrap());
+        s.s = HLTimespec::new(0, 1, 0, u16::MAX, 0);
+
+        let t = s.get_time();
+        assert_eq!(t, hlts(1, 1, 0));
+    }
+
+    #[test]
+    fn get_time_carries_nanosecond_overflow_into_seconds() {
+        let mut times = vec![ts(1, 999999999)];
+        let mut s = State::new_with(move || times.pop().unwrap());
+        s.s = HLTimespec::new(0, 1, 999999999, u16::MAX, 0);
+
+        let t = s.get_time();
+        assert_eq!(t, hlts(2, 0, 0));
+    }
+
+    #[test]
+    fn update_carries_logical_overflow_into_wall() {
+        let mut times = vec![ts(1, 0)];
+        let mut s = State::new_with(move || times.pop().unwrap());
+        s.s = HLTimespec::new(0, 1, 0, u16::MAX, 0);
+
+        let t = s.update(hlts(1, 0, 0)).unwrap();
+        assert_eq!(t, hlts(1, 1, 0));
+    }
+
+    #[test]
+    fn encode_decode_round_trips() {
+        let ts = HLTimespec::new(0, 12345, 6789, 10, 42);
+        assert_eq!(HLTimespec::decode(&ts.encode()).unwrap(), ts);
+    }
+
+    #[test]
+    fn encode_decode_round_trips_negative_seconds() {
+        let ts = HLTimespec::new(0, -12345, 6789, 10, 42);
+        assert_eq!(HLTimespec::decode(&ts.encode()).unwrap(), ts);
+    }
+
+    #[test]
+    fn encode_preserves_ord() {
+        let lesser = HLTimespec::new(0, 1, 0, 0, 0);
+        let greater = HLTimespec::new(0, 1, 0, 0, 1);
+        assert!(lesser < greater);
+        assert!(lesser.encode() < greater.encode());
+
+        let lesser = HLTimespec::new(0, -1, 0, 0, 0);
+        let greater = HLTimespec::new(0, 1, 0, 0, 0);
+        assert!(lesser < greater);
+        assert!(lesser.encode() < greater.encode());
+    }
+
+    #[test]
+    fn decode_rejects_wrong_length() {
+        match HLTimespec::decode(&[0u8; 4]) {
+            Err(ParseError::InvalidLength { expected, found }) => {
+                assert_eq!(expected, ::ENCODED_LEN);
+                assert_eq!(found, 4);
+            }
+            other => panic!("expected InvalidLength, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn display_from_str_round_trips() {
+        let ts = hlts(1, 2, 3);
+        assert_eq!(HLTimespec::from_str(&ts.to_string()).unwrap(), ts);
+    }
+
+    #[test]
+    fn from_str_rejects_malformed_input() {
+        assert_eq!(HLTimespec::from_str("not a timestamp"), Err(ParseError::InvalidFormat));
+    }
+
+    #[test]
+    fn set_epoch_dominates_wall_comparison() {
+        let far_future = HLTimespec::new(0, i64::max_value(), 0, 0, 0);
+        let mut s = State::new_with(|| ts(1, 0));
+        s.set_epoch(1);
+
+        let t = s.get_time();
+        assert!(t > far_future);
+        assert_eq!(t, HLTimespec::new(1, 1, 0, 0, 0));
+    }
+
+    #[test]
+    fn update_adopts_strictly_greater_epoch() {
+        let mut times = vec![ts(1, 0)];
+        let mut s = State::new_with(move || times.pop().unwrap());
+
+        // `id` is this node's own identity, not the remote event's, so it
+        // stays unchanged even as the epoch and wall time are adopted.
+        let event = HLTimespec::new(2, 1, 0, 5, 7);
+        let t = s.update(event).unwrap();
+        assert_eq!(t, HLTimespec::new(2, 1, 0, 6, 0));
+    }
+
+    #[test]
+    fn update_ignores_event_at_lower_epoch() {
+        let mut times = vec![ts(1, 0)];
+        let mut s = State::new_with(move || times.pop().unwrap());
+        s.s = HLTimespec::new(3, 1, 0, 0, 0);
+
+        let stale_event = HLTimespec::new(1, 999, 0, 0, 0);
+        let t = s.update(stale_event).unwrap();
+        assert_eq!(t, HLTimespec::new(3, 1, 0, 1, 0));
+    }
+
+    #[test]
+    fn update_bypasses_max_diff_when_adopting_a_greater_epoch() {
+        let mut times = vec![ts(1, 0)];
+        let mut s = State::new_with_max_diff(move || times.pop().unwrap(), time::Duration::seconds(5));
+
+        // Far ahead in wall time, but that's expected when recovering via a
+        // higher epoch, so it must not be rejected as too-far-ahead.
+        let event = HLTimespec::new(1, 100000, 0, 0, 0);
+        let t = s.update(event).unwrap();
+        assert_eq!(t, HLTimespec::new(1, 100000, 0, 1, 0));
+    }
 }